@@ -7,6 +7,10 @@ use crate::{
     Matrix, Pixmap, Quad, Rect, TextPage, TextPageFlags,
 };
 
+mod command;
+
+pub use command::{ClipKind, DisplayCommand};
+
 #[derive(Debug)]
 pub struct DisplayList {
     pub(crate) inner: *mut fz_display_list,
@@ -40,6 +44,98 @@ impl DisplayList {
         .map(|inner| unsafe { Pixmap::from_raw(inner) })
     }
 
+    pub fn to_pixmap_parallel(
+        &self,
+        ctm: &Matrix,
+        cs: &Colorspace,
+        alpha: bool,
+        n_threads: usize,
+    ) -> Result<Pixmap, Error> {
+        let n_threads = n_threads.max(1);
+        let bbox = transform_rect(self.bounds(), ctm);
+
+        // wasm32 has no real thread support in this crate (see
+        // `test_multi_threaded_display_list_search` below), so band-parallel
+        // rasterization degrades to the single-threaded path there.
+        if cfg!(target_arch = "wasm32") || n_threads == 1 || bbox.y1 - bbox.y0 <= 1.0 {
+            return self.to_pixmap(ctm, cs, alpha);
+        }
+
+        let bbox = irect_outward(bbox);
+        let bands = split_into_bands(bbox, n_threads);
+
+        let full_pixmap = unsafe {
+            ffi_try!(fz_new_pixmap_with_bbox(
+                context(),
+                cs.inner,
+                bbox,
+                ptr::null_mut(),
+                alpha as i32
+            ))
+        }
+        .map(|inner| unsafe { Pixmap::from_raw(inner) })?;
+
+        let band_pixmaps = std::thread::scope(|scope| {
+            bands
+                .iter()
+                .map(|band| {
+                    let band = *band;
+                    scope.spawn(move || -> Result<Pixmap, Error> {
+                        let mut pixmap = unsafe {
+                            ffi_try!(fz_new_pixmap_with_bbox(
+                                context(),
+                                cs.inner,
+                                band,
+                                ptr::null_mut(),
+                                alpha as i32
+                            ))
+                        }
+                        .map(|inner| unsafe { Pixmap::from_raw(inner) })?;
+                        // Same background as `to_pixmap`: transparent with alpha, white without.
+                        if alpha {
+                            pixmap.clear()?;
+                        } else {
+                            pixmap.clear_with(0xff)?;
+                        }
+
+                        // `ctm` is applied by `run` below; the device itself adds no transform.
+                        let device = unsafe {
+                            ffi_try!(fz_new_draw_device(
+                                context(),
+                                (&Matrix::IDENTITY).into(),
+                                pixmap.inner
+                            ))
+                        }
+                        .map(|dev| unsafe { Device::from_raw(dev) })?;
+
+                        self.run(&device, ctm, rect_of(band))?;
+
+                        unsafe { ffi_try!(fz_close_device(context(), device.dev)) }?;
+
+                        Ok(pixmap)
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().expect("rasterization thread panicked"))
+                .collect::<Result<Vec<_>, Error>>()
+        })?;
+
+        for (band, band_pixmap) in bands.iter().zip(band_pixmaps) {
+            unsafe {
+                ffi_try!(fz_copy_pixmap_rect(
+                    context(),
+                    full_pixmap.inner,
+                    band_pixmap.inner,
+                    *band,
+                    ptr::null_mut()
+                ))
+            }?;
+        }
+
+        Ok(full_pixmap)
+    }
+
     pub fn to_text_page(&self, opts: TextPageFlags) -> Result<TextPage, Error> {
         let inner = unsafe {
             ffi_try!(mupdf_display_list_to_text_page(
@@ -90,6 +186,27 @@ impl DisplayList {
         }
     }
 
+    pub fn reset(&mut self, media_box: Rect) -> Result<(), Error> {
+        let new_inner = unsafe { ffi_try!(mupdf_new_display_list(context(), media_box.into())) }?;
+        unsafe {
+            if !self.inner.is_null() {
+                fz_drop_display_list(context(), self.inner);
+            }
+        }
+        self.inner = new_inner;
+        Ok(())
+    }
+
+    pub fn to_commands(&self) -> Result<Vec<DisplayCommand>, Error> {
+        unsafe {
+            let dev = command::new_recording_device()?;
+            let device = Device::from_raw(dev);
+            let bounds = self.bounds();
+            self.run(&device, &Matrix::IDENTITY, bounds)?;
+            Ok(command::take_commands(dev))
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         unsafe { fz_display_list_is_empty(context(), self.inner) > 0 }
     }
@@ -109,6 +226,53 @@ impl DisplayList {
         }
         .and_then(|quads| unsafe { rust_vec_from_ffi_ptr(quads, hit_count) })
     }
+
+    // MuPDF's `fz_search_display_list` has no cookie/abort hook the way
+    // `fz_run_display_list` does for rendering, so a single bounded search
+    // can't be interrupted mid-call; `cookie` can't change what this call
+    // returns. It's accepted anyway so callers can pass the same cookie they
+    // use for rendering, and so `search_all_with_cookie` below has something
+    // to check between calls, which is the only cancellation granularity the
+    // underlying API actually supports.
+    pub fn search_with_cookie(
+        &self,
+        needle: &str,
+        hit_max: u32,
+        cookie: &Cookie,
+    ) -> Result<FzArray<Quad>, Error> {
+        let _ = cookie;
+        self.search(needle, hit_max)
+    }
+
+    pub fn search_all(&self, needle: &str) -> Result<FzArray<Quad>, Error> {
+        let mut hit_max = 16u32;
+        loop {
+            let hits = self.search(needle, hit_max)?;
+            if (hits.len() as u32) < hit_max {
+                return Ok(hits);
+            }
+            hit_max = hit_max.saturating_mul(2);
+        }
+    }
+
+    // Checks `cookie` between growth iterations rather than fabricating an
+    // empty result: the only array this can ever return is one that came back
+    // from a real `search_with_cookie` call above.
+    pub fn search_all_with_cookie(
+        &self,
+        needle: &str,
+        cookie: &Cookie,
+    ) -> Result<FzArray<Quad>, Error> {
+        let mut hit_max = 16u32;
+        let mut hits = self.search_with_cookie(needle, hit_max, cookie)?;
+        loop {
+            if cookie.aborted() || (hits.len() as u32) < hit_max {
+                return Ok(hits);
+            }
+            hit_max = hit_max.saturating_mul(2);
+            hits = self.search_with_cookie(needle, hit_max, cookie)?;
+        }
+    }
 }
 
 impl Drop for DisplayList {
@@ -125,6 +289,68 @@ impl Drop for DisplayList {
 unsafe impl Send for DisplayList {}
 unsafe impl Sync for DisplayList {}
 
+fn transform_rect(rect: Rect, matrix: &Matrix) -> Rect {
+    let corners = [
+        (rect.x0, rect.y0),
+        (rect.x1, rect.y0),
+        (rect.x0, rect.y1),
+        (rect.x1, rect.y1),
+    ];
+
+    let mut x0 = f32::MAX;
+    let mut y0 = f32::MAX;
+    let mut x1 = f32::MIN;
+    let mut y1 = f32::MIN;
+    for (x, y) in corners {
+        let tx = matrix.a * x + matrix.c * y + matrix.e;
+        let ty = matrix.b * x + matrix.d * y + matrix.f;
+        x0 = x0.min(tx);
+        y0 = y0.min(ty);
+        x1 = x1.max(tx);
+        y1 = y1.max(ty);
+    }
+
+    Rect { x0, y0, x1, y1 }
+}
+
+fn irect_outward(rect: Rect) -> fz_irect {
+    fz_irect {
+        x0: rect.x0.floor() as i32,
+        y0: rect.y0.floor() as i32,
+        x1: rect.x1.ceil() as i32,
+        y1: rect.y1.ceil() as i32,
+    }
+}
+
+fn rect_of(irect: fz_irect) -> Rect {
+    Rect {
+        x0: irect.x0 as f32,
+        y0: irect.y0 as f32,
+        x1: irect.x1 as f32,
+        y1: irect.y1 as f32,
+    }
+}
+
+fn split_into_bands(bbox: fz_irect, n_bands: usize) -> Vec<fz_irect> {
+    let total_height = (bbox.y1 - bbox.y0).max(1) as usize;
+    let band_height = ((total_height + n_bands - 1) / n_bands).max(1) as i32;
+
+    let mut bands = Vec::with_capacity(n_bands);
+    let mut y = bbox.y0;
+    while y < bbox.y1 {
+        let y1 = (y + band_height).min(bbox.y1);
+        bands.push(fz_irect {
+            x0: bbox.x0,
+            y0: y,
+            x1: bbox.x1,
+            y1,
+        });
+        y = y1;
+    }
+
+    bands
+}
+
 #[cfg(test)]
 mod test {
     use crate::{document::test_document, Document};
@@ -164,6 +390,126 @@ mod test {
         assert_eq!(hits.len(), 0);
     }
 
+    #[test]
+    fn test_display_list_to_pixmap_parallel_matches_single_threaded() {
+        use crate::{Colorspace, Matrix};
+
+        let doc = test_document!("..", "files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let list = page0.to_display_list(false).unwrap();
+        let cs = Colorspace::device_rgb();
+        let ctm = Matrix::IDENTITY;
+
+        let single_threaded = list.to_pixmap(&ctm, &cs, false).unwrap();
+        let parallel = list.to_pixmap_parallel(&ctm, &cs, false, 4).unwrap();
+
+        assert_eq!(single_threaded.width(), parallel.width());
+        assert_eq!(single_threaded.height(), parallel.height());
+        assert_eq!(single_threaded.samples(), parallel.samples());
+    }
+
+    #[test]
+    fn test_display_list_search_with_cookie() {
+        use crate::Cookie;
+
+        let doc = test_document!("..", "files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let list = page0.to_display_list(false).unwrap();
+
+        let cookie = Cookie::new().unwrap();
+        let hits = list.search_with_cookie("Dummy", 1, &cookie).unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[test]
+    fn test_display_list_search_with_cookie_aborted() {
+        use crate::Cookie;
+
+        let doc = test_document!("..", "files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let list = page0.to_display_list(false).unwrap();
+
+        // `fz_search_display_list` has no mid-call abort hook, so a single
+        // `search_with_cookie` call still runs to completion and returns its
+        // real hits even once the cookie is aborted.
+        let cookie = Cookie::new().unwrap();
+        cookie.abort();
+        let hits = list.search_with_cookie("Dummy", 1, &cookie).unwrap();
+        assert_eq!(hits.len(), 1);
+
+        // `search_all_with_cookie` stops growing `hit_max` once aborted,
+        // returning whatever the first real search already found.
+        let hits = list.search_all_with_cookie("Dummy", &cookie).unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[test]
+    fn test_display_list_search_all() {
+        let doc = test_document!("..", "files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let list = page0.to_display_list(false).unwrap();
+
+        let hits = list.search_all("Dummy").unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[test]
+    fn test_display_list_to_commands() {
+        use crate::DisplayCommand;
+
+        let doc = test_document!("..", "files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let list = page0.to_display_list(false).unwrap();
+
+        let commands = list.to_commands().unwrap();
+        assert!(!commands.is_empty());
+        assert!(commands
+            .iter()
+            .any(|cmd| matches!(cmd, DisplayCommand::FillText { .. })));
+    }
+
+    #[test]
+    fn test_display_list_to_commands_clip_nesting_balanced() {
+        use crate::DisplayCommand;
+
+        let doc = test_document!("..", "files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let list = page0.to_display_list(false).unwrap();
+
+        let commands = list.to_commands().unwrap();
+        let mut clip_depth = 0i32;
+        let mut group_depth = 0i32;
+        for cmd in &commands {
+            match cmd {
+                DisplayCommand::Clip { .. } => clip_depth += 1,
+                DisplayCommand::PopClip => {
+                    clip_depth -= 1;
+                    assert!(clip_depth >= 0, "PopClip with no matching open Clip");
+                }
+                DisplayCommand::BeginGroup { .. } => group_depth += 1,
+                DisplayCommand::EndGroup => {
+                    group_depth -= 1;
+                    assert!(group_depth >= 0, "EndGroup with no matching BeginGroup");
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(clip_depth, 0);
+        assert_eq!(group_depth, 0);
+    }
+
+    #[test]
+    fn test_display_list_reset() {
+        let doc = test_document!("..", "files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let mut list = page0.to_display_list(false).unwrap();
+        assert!(!list.is_empty());
+
+        let media_box = list.bounds();
+        list.reset(media_box).unwrap();
+        assert!(list.is_empty());
+    }
+
     #[test]
     #[cfg(not(target_arch = "wasm32"))]
     fn test_multi_threaded_display_list_search() {
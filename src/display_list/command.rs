@@ -0,0 +1,350 @@
+use std::{ffi::CStr, ptr, slice};
+
+use mupdf_sys::*;
+
+use crate::{context, Error, Matrix, Rect};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayCommand {
+    FillPath {
+        ctm: Matrix,
+        rect: Rect,
+        even_odd: bool,
+        colorspace: String,
+        color: Vec<f32>,
+        alpha: f32,
+    },
+    StrokePath {
+        ctm: Matrix,
+        rect: Rect,
+        line_width: f32,
+        colorspace: String,
+        color: Vec<f32>,
+        alpha: f32,
+    },
+    FillText {
+        ctm: Matrix,
+        rect: Rect,
+        text: String,
+        colorspace: String,
+        color: Vec<f32>,
+        alpha: f32,
+    },
+    FillImage {
+        ctm: Matrix,
+        rect: Rect,
+        alpha: f32,
+    },
+    Clip {
+        ctm: Matrix,
+        rect: Rect,
+        kind: ClipKind,
+    },
+    PopClip,
+    BeginGroup {
+        rect: Rect,
+        isolated: bool,
+        knockout: bool,
+        alpha: f32,
+    },
+    EndGroup,
+}
+
+// MuPDF opens a clip region through one of five clip callbacks (clipping to
+// a fill path, a stroked path, text, stroked text, or an image mask), and all
+// of them are closed by the single shared `pop_clip` callback. Every clip
+// opener below pushes a `Clip`, so each of these is matched by its `PopClip`.
+// A soft mask (`begin_mask`/`end_mask`) is also closed by `pop_clip`, but this
+// device does not record masks, so the `PopClip` ending a soft mask has no
+// matching `Clip`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClipKind {
+    Path { even_odd: bool },
+    StrokePath,
+    Text,
+    StrokeText,
+    ImageMask,
+}
+
+// A `fz_device` whose extra state is the recorded command list. MuPDF derived
+// devices are a C struct with the `fz_device` header first, followed by
+// whatever extra fields the implementation needs; `fz_new_device_of_size`
+// allocates room for both and we fill in the callbacks ourselves.
+#[repr(C)]
+struct RecordingDevice {
+    base: fz_device,
+    commands: *mut Vec<DisplayCommand>,
+}
+
+unsafe fn colorspace_name_and_color(
+    cs: *mut fz_colorspace,
+    color: *const f32,
+) -> (String, Vec<f32>) {
+    if cs.is_null() {
+        return (String::from("none"), Vec::new());
+    }
+    let name = fz_colorspace_name(context(), cs);
+    let name = if name.is_null() {
+        String::from("none")
+    } else {
+        CStr::from_ptr(name).to_string_lossy().into_owned()
+    };
+    let n = fz_colorspace_n(context(), cs) as usize;
+    let color = if color.is_null() {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(color, n).to_vec()
+    };
+    (name, color)
+}
+
+unsafe fn text_to_string(text: *const fz_text) -> String {
+    let mut out = String::new();
+    let mut span = (*text).head;
+    while !span.is_null() {
+        let items = slice::from_raw_parts((*span).items, (*span).len as usize);
+        for item in items {
+            if item.ucs >= 0 {
+                if let Some(ch) = char::from_u32(item.ucs as u32) {
+                    out.push(ch);
+                }
+            }
+        }
+        span = (*span).next;
+    }
+    out
+}
+
+unsafe fn recorder_of(dev: *mut fz_device) -> &'static mut Vec<DisplayCommand> {
+    let dev = dev as *mut RecordingDevice;
+    &mut *(*dev).commands
+}
+
+unsafe extern "C" fn fill_path(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    path: *const fz_path,
+    even_odd: i32,
+    ctm: fz_matrix,
+    colorspace: *mut fz_colorspace,
+    color: *const f32,
+    alpha: f32,
+    _color_params: fz_color_params,
+) {
+    let rect = fz_bound_path(context(), path, ptr::null(), ctm);
+    let (colorspace, color) = colorspace_name_and_color(colorspace, color);
+    recorder_of(dev).push(DisplayCommand::FillPath {
+        ctm: ctm.into(),
+        rect: rect.into(),
+        even_odd: even_odd != 0,
+        colorspace,
+        color,
+        alpha,
+    });
+}
+
+unsafe extern "C" fn stroke_path(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    path: *const fz_path,
+    stroke: *const fz_stroke_state,
+    ctm: fz_matrix,
+    colorspace: *mut fz_colorspace,
+    color: *const f32,
+    alpha: f32,
+    _color_params: fz_color_params,
+) {
+    let rect = fz_bound_path(context(), path, stroke, ctm);
+    let (colorspace, color) = colorspace_name_and_color(colorspace, color);
+    let line_width = if stroke.is_null() {
+        0.0
+    } else {
+        (*stroke).linewidth
+    };
+    recorder_of(dev).push(DisplayCommand::StrokePath {
+        ctm: ctm.into(),
+        rect: rect.into(),
+        line_width,
+        colorspace,
+        color,
+        alpha,
+    });
+}
+
+unsafe extern "C" fn fill_text(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    text: *const fz_text,
+    ctm: fz_matrix,
+    colorspace: *mut fz_colorspace,
+    color: *const f32,
+    alpha: f32,
+    _color_params: fz_color_params,
+) {
+    let rect = fz_bound_text(context(), text, ptr::null(), ctm);
+    let (colorspace, color) = colorspace_name_and_color(colorspace, color);
+    recorder_of(dev).push(DisplayCommand::FillText {
+        ctm: ctm.into(),
+        rect: rect.into(),
+        text: text_to_string(text),
+        colorspace,
+        color,
+        alpha,
+    });
+}
+
+unsafe extern "C" fn fill_image(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    _image: *mut fz_image,
+    ctm: fz_matrix,
+    alpha: f32,
+    _color_params: fz_color_params,
+) {
+    let unit_square = fz_rect {
+        x0: 0.0,
+        y0: 0.0,
+        x1: 1.0,
+        y1: 1.0,
+    };
+    let rect = fz_transform_rect(unit_square, ctm);
+    recorder_of(dev).push(DisplayCommand::FillImage {
+        ctm: ctm.into(),
+        rect: rect.into(),
+        alpha,
+    });
+}
+
+unsafe extern "C" fn clip_path(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    _path: *const fz_path,
+    even_odd: i32,
+    ctm: fz_matrix,
+    scissor: fz_rect,
+) {
+    recorder_of(dev).push(DisplayCommand::Clip {
+        ctm: ctm.into(),
+        rect: scissor.into(),
+        kind: ClipKind::Path {
+            even_odd: even_odd != 0,
+        },
+    });
+}
+
+unsafe extern "C" fn clip_stroke_path(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    _path: *const fz_path,
+    _stroke: *const fz_stroke_state,
+    ctm: fz_matrix,
+    scissor: fz_rect,
+) {
+    recorder_of(dev).push(DisplayCommand::Clip {
+        ctm: ctm.into(),
+        rect: scissor.into(),
+        kind: ClipKind::StrokePath,
+    });
+}
+
+unsafe extern "C" fn clip_text(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    _text: *const fz_text,
+    ctm: fz_matrix,
+    scissor: fz_rect,
+) {
+    recorder_of(dev).push(DisplayCommand::Clip {
+        ctm: ctm.into(),
+        rect: scissor.into(),
+        kind: ClipKind::Text,
+    });
+}
+
+unsafe extern "C" fn clip_stroke_text(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    _text: *const fz_text,
+    _stroke: *const fz_stroke_state,
+    ctm: fz_matrix,
+    scissor: fz_rect,
+) {
+    recorder_of(dev).push(DisplayCommand::Clip {
+        ctm: ctm.into(),
+        rect: scissor.into(),
+        kind: ClipKind::StrokeText,
+    });
+}
+
+unsafe extern "C" fn clip_image_mask(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    _image: *mut fz_image,
+    ctm: fz_matrix,
+    scissor: fz_rect,
+) {
+    recorder_of(dev).push(DisplayCommand::Clip {
+        ctm: ctm.into(),
+        rect: scissor.into(),
+        kind: ClipKind::ImageMask,
+    });
+}
+
+unsafe extern "C" fn pop_clip(_ctx: *mut fz_context, dev: *mut fz_device) {
+    recorder_of(dev).push(DisplayCommand::PopClip);
+}
+
+unsafe extern "C" fn begin_group(
+    _ctx: *mut fz_context,
+    dev: *mut fz_device,
+    area: fz_rect,
+    _colorspace: *mut fz_colorspace,
+    isolated: i32,
+    knockout: i32,
+    _blendmode: i32,
+    alpha: f32,
+) {
+    recorder_of(dev).push(DisplayCommand::BeginGroup {
+        rect: area.into(),
+        isolated: isolated != 0,
+        knockout: knockout != 0,
+        alpha,
+    });
+}
+
+unsafe extern "C" fn end_group(_ctx: *mut fz_context, dev: *mut fz_device) {
+    recorder_of(dev).push(DisplayCommand::EndGroup);
+}
+
+unsafe extern "C" fn drop_device(_ctx: *mut fz_context, dev: *mut fz_device) {
+    let dev = dev as *mut RecordingDevice;
+    drop(Box::from_raw((*dev).commands));
+}
+
+pub(crate) unsafe fn new_recording_device() -> Result<*mut fz_device, Error> {
+    let raw = ffi_try!(fz_new_device_of_size(
+        context(),
+        std::mem::size_of::<RecordingDevice>() as _
+    ))?;
+    let dev = raw as *mut RecordingDevice;
+    (*dev).commands = Box::into_raw(Box::new(Vec::new()));
+    (*dev).base.fill_path = Some(fill_path);
+    (*dev).base.stroke_path = Some(stroke_path);
+    (*dev).base.fill_text = Some(fill_text);
+    (*dev).base.fill_image = Some(fill_image);
+    (*dev).base.clip_path = Some(clip_path);
+    (*dev).base.clip_stroke_path = Some(clip_stroke_path);
+    (*dev).base.clip_text = Some(clip_text);
+    (*dev).base.clip_stroke_text = Some(clip_stroke_text);
+    (*dev).base.clip_image_mask = Some(clip_image_mask);
+    (*dev).base.pop_clip = Some(pop_clip);
+    (*dev).base.begin_group = Some(begin_group);
+    (*dev).base.end_group = Some(end_group);
+    (*dev).base.drop_device = Some(drop_device);
+    Ok(dev as *mut fz_device)
+}
+
+pub(crate) unsafe fn take_commands(dev: *mut fz_device) -> Vec<DisplayCommand> {
+    let dev = dev as *mut RecordingDevice;
+    std::mem::take(&mut *(*dev).commands)
+}